@@ -1,12 +1,18 @@
 use anyhow::{Context, Result};
-use clap::{ArgAction, Parser};
+use chrono::{DateTime, Local};
+use clap::{ArgAction, Parser, Subcommand};
 use dirs_next::download_dir;
 use once_cell::sync::Lazy;
-use std::collections::{BTreeMap, BTreeSet};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Cross-platform Downloads organizer.
 ///
@@ -14,6 +20,9 @@ use std::path::{Path, PathBuf};
 #[derive(Parser, Debug)]
 #[command(name = "foldean", about = "Organize your Downloads into tidy folders", version)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory to organize (defaults to OS Downloads directory)
     #[arg(short, long)]
     dir: Option<PathBuf>,
@@ -29,6 +38,57 @@ struct Cli {
     /// Maximum depth to scan (0 means only the target directory)
     #[arg(long, default_value_t = 0)]
     depth: usize,
+
+    /// Classify files by sniffing their content (magic bytes) instead of relying on extension
+    #[arg(long, action = ArgAction::SetTrue)]
+    by_content: bool,
+
+    /// Path to a TOML config declaring custom categories (defaults to the platform config dir)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// How to handle a move colliding with a byte-identical file already in the destination
+    #[arg(long, value_enum, default_value_t = OnDuplicate::Rename)]
+    on_duplicate: OnDuplicate,
+
+    /// Follow symlinked directories during recursive scans (off by default)
+    #[arg(long, action = ArgAction::SetTrue)]
+    follow_symlinks: bool,
+
+    /// Nest files under a date subfolder (by modification time) within their category
+    #[arg(long, action = ArgAction::SetTrue)]
+    by_date: bool,
+
+    /// strftime-style pattern for the --by-date subfolder, e.g. "%Y/%m"
+    #[arg(long, default_value = "%Y/%m")]
+    date_format: String,
+
+    /// Archive files untouched for longer than --older-than into per-category .tar.xz
+    /// bundles instead of moving them
+    #[arg(long, action = ArgAction::SetTrue, requires = "older_than")]
+    archive: bool,
+
+    /// Age threshold for --archive, e.g. "90d", "2w", "6h"
+    #[arg(long)]
+    older_than: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Undo the most recent applied run, moving files back to their original locations
+    Undo,
+}
+
+/// What to do when a move collides with a file of the same name whose
+/// content is confirmed (via size then hash) to be byte-identical.
+#[derive(Copy, Clone, Debug, clap::ValueEnum)]
+enum OnDuplicate {
+    /// Leave the source where it is.
+    Skip,
+    /// Keep both copies, appending " (1)", " (2)", ... to the new one.
+    Rename,
+    /// Remove the source; the destination already has this content.
+    Delete,
 }
 
 /// Folder categories keyed by folder name with supported extensions (lowercase, no dot)
@@ -36,7 +96,7 @@ static CATEGORY_EXTENSIONS: Lazy<BTreeMap<&'static str, BTreeSet<&'static str>>>
     let mut map: BTreeMap<&'static str, BTreeSet<&'static str>> = BTreeMap::new();
     let mut add = |category: &'static str, exts: &[&'static str]| {
         map.entry(category)
-            .or_insert_with(BTreeSet::new)
+            .or_default()
             .extend(exts.iter().copied());
     };
 
@@ -62,7 +122,11 @@ static CATEGORY_EXTENSIONS: Lazy<BTreeMap<&'static str, BTreeSet<&'static str>>>
 
     // Archives & installers
     add("Archives", &["zip", "rar", "7z", "tar", "gz", "bz2", "xz"]);
-    add("Installer", &["dmg", "pkg", "msi", "exe", "deb", "rpm", "appimage", "app"]);
+    add(
+        "Installer",
+        &["dmg", "pkg", "msi", "exe", "deb", "rpm", "appimage", "app", "scr"],
+    );
+    add("Libraries", &["dll", "so", "dylib"]);
 
     // Design/graphics
     add("Design", &["psd", "ai", "xd", "fig", "sketch"]);
@@ -70,24 +134,168 @@ static CATEGORY_EXTENSIONS: Lazy<BTreeMap<&'static str, BTreeSet<&'static str>>>
     map
 });
 
+/// User-facing schema for `config.toml`: a list of rules merged over the
+/// built-in [`CATEGORY_EXTENSIONS`] defaults.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    rules: Vec<CategoryRule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryRule {
+    folder: String,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    name_regex: Option<String>,
+}
+
+/// Fully resolved classification rules: the merged extension map plus
+/// compiled filename regexes, which are checked first and win over
+/// extension matching.
+struct Rules {
+    extensions: BTreeMap<String, BTreeSet<String>>,
+    name_rules: Vec<(Regex, String)>,
+}
+
+impl Rules {
+    fn load(explicit: Option<&Path>) -> Result<Rules> {
+        let config = load_config(explicit)?;
+        Rules::resolve(config)
+    }
+
+    fn resolve(config: ConfigFile) -> Result<Rules> {
+        let mut extensions: BTreeMap<String, BTreeSet<String>> = CATEGORY_EXTENSIONS
+            .iter()
+            .map(|(category, exts)| {
+                (category.to_string(), exts.iter().map(|e| e.to_string()).collect())
+            })
+            .collect();
+        let mut name_rules = Vec::new();
+
+        for rule in config.rules {
+            if !rule.extensions.is_empty() {
+                extensions
+                    .entry(rule.folder.clone())
+                    .or_default()
+                    .extend(rule.extensions.iter().map(|e| e.to_lowercase()));
+            }
+            if let Some(pattern) = rule.name_regex {
+                let re = Regex::new(&pattern)
+                    .with_context(|| format!("Invalid name_regex `{}` for folder `{}`", pattern, rule.folder))?;
+                name_rules.push((re, rule.folder));
+            }
+        }
+
+        Ok(Rules { extensions, name_rules })
+    }
+
+    /// Match a file name against the user's `name_regex` rules, in config order.
+    fn match_name(&self, file_name: &str) -> Option<&str> {
+        self.name_rules
+            .iter()
+            .find(|(re, _)| re.is_match(file_name))
+            .map(|(_, folder)| folder.as_str())
+    }
+
+    fn match_extension(&self, ext: &str) -> Option<&str> {
+        if ext.is_empty() {
+            return None;
+        }
+        self.extensions
+            .iter()
+            .find(|(_, exts)| exts.contains(ext))
+            .map(|(category, _)| category.as_str())
+    }
+}
+
+/// The platform config dir's `foldean/` subdirectory, used for both
+/// `config.toml` and the move journal. `None` if the platform has no
+/// resolvable config dir.
+fn foldean_state_dir() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("foldean"))
+}
+
+/// Load `config.toml` from `explicit` if given, else from the platform config
+/// dir. A missing file is not an error: it just means no custom rules.
+fn load_config(explicit: Option<&Path>) -> Result<ConfigFile> {
+    let path = match explicit {
+        Some(path) => Some(path.to_path_buf()),
+        None => foldean_state_dir().map(|dir| dir.join("config.toml")),
+    };
+
+    let Some(path) = path else {
+        return Ok(ConfigFile::default());
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => {
+            toml::from_str(&contents).with_context(|| format!("Parsing config {}", path.display()))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+        Err(err) => Err(err).with_context(|| format!("Reading config {}", path.display())),
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    if matches!(cli.command, Some(Command::Undo)) {
+        return run_undo();
+    }
+
     let target_dir = cli
         .dir
-        .or_else(|| download_dir())
+        .or_else(download_dir)
         .context("Could not resolve Downloads directory. Pass --dir explicitly.")?;
 
-    let plan = build_plan(&target_dir, cli.depth, cli.include_hidden)?;
+    let rules = Rules::load(cli.config.as_deref())?;
+    let options = ScanOptions {
+        depth: cli.depth,
+        include_hidden: cli.include_hidden,
+        by_content: cli.by_content,
+        on_duplicate: cli.on_duplicate,
+        follow_symlinks: cli.follow_symlinks,
+        by_date: cli.by_date,
+        date_format: cli.date_format,
+    };
+
+    if cli.archive {
+        let max_age = parse_age(cli.older_than.as_deref().expect("clap requires older_than with archive"))?;
+        let cutoff = SystemTime::now()
+            .checked_sub(max_age)
+            .context("--older-than is too large")?;
+        return run_archive(&target_dir, &rules, &options, cutoff, cli.apply);
+    }
+
+    let scan = build_plan(&target_dir, &rules, &options)?;
+    let ScanResult { plan, warnings } = scan;
+
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
 
     if plan.is_empty() {
         println!("Nothing to organize in {}", target_dir.display());
         return Ok(());
     }
 
-    println!("Planned moves ({}):", plan.len());
-    for (from, to) in &plan {
-        println!("  {} -> {}", from.display(), to.display());
+    println!("Planned actions ({}):", plan.len());
+    for action in &plan {
+        match action {
+            Action::Move { from, to } => println!("  {} -> {}", from.display(), to.display()),
+            Action::Skip { from, duplicate_of } => println!(
+                "  {} == {} (duplicate, skipping)",
+                from.display(),
+                duplicate_of.display()
+            ),
+            Action::Delete { from, duplicate_of } => println!(
+                "  {} == {} (duplicate, deleting source)",
+                from.display(),
+                duplicate_of.display()
+            ),
+        }
     }
 
     if cli.apply {
@@ -100,70 +308,767 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn build_plan(dir: &Path, depth: usize, include_hidden: bool) -> Result<Vec<(PathBuf, PathBuf)>> {
-    let mut moves: Vec<(PathBuf, PathBuf)> = Vec::new();
+/// Parse an `--older-than` duration like "90d", "2w", or "6h".
+fn parse_age(spec: &str) -> Result<Duration> {
+    let trimmed = spec.trim();
+    let split_at = trimmed
+        .len()
+        .checked_sub(1)
+        .filter(|_| !trimmed.is_empty())
+        .context("--older-than must not be empty")?;
+    let (amount, unit) = trimmed.split_at(split_at);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("Invalid --older-than value `{}`; expected e.g. \"90d\"", spec))?;
+
+    let seconds = match unit {
+        "h" => amount * 3_600,
+        "d" => amount * 86_400,
+        "w" => amount * 86_400 * 7,
+        other => anyhow::bail!("Unknown --older-than unit `{}`; use h, d, or w", other),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// A per-category bundle of stale files slated for compression.
+struct ArchiveJob {
+    tarball: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+/// Run archival mode: group files older than `cutoff` by category and pack
+/// each group into a `Category-Year.tar.xz` under `dir/Archives`, removing
+/// the originals only after the archive has been verified.
+fn run_archive(dir: &Path, rules: &Rules, options: &ScanOptions, cutoff: SystemTime, apply: bool) -> Result<()> {
+    let mut grouped: BTreeMap<&str, Vec<PathBuf>> = BTreeMap::new();
+    collect_stale_files(dir, rules, options, cutoff, options.depth, &mut grouped)?;
+
+    if grouped.is_empty() {
+        println!("Nothing old enough to archive in {}", dir.display());
+        return Ok(());
+    }
+
+    let year = Local::now().format("%Y");
+    let jobs: Vec<ArchiveJob> = grouped
+        .into_iter()
+        .map(|(category, mut files)| {
+            files.sort();
+            let tarball = dir.join("Archives").join(format!("{}-{}.tar.xz", category, year));
+            ArchiveJob { tarball, files }
+        })
+        .collect();
 
+    println!("Planned archives ({} bundles):", jobs.len());
+    for job in &jobs {
+        println!("  {} ({} files):", job.tarball.display(), job.files.len());
+        for file in &job.files {
+            println!("    {}", file.display());
+        }
+    }
+
+    if apply {
+        for job in jobs {
+            create_archive(dir, &job.tarball, &job.files)?;
+            verify_archive(&job.tarball, &job.files)?;
+            for file in &job.files {
+                fs::remove_file(file).with_context(|| format!("Remove {}", file.display()))?;
+            }
+        }
+        println!("Done.");
+    } else {
+        println!("Dry run. Pass --apply to create archives and remove originals.");
+    }
+
+    Ok(())
+}
+
+/// Recursively collect files older than `cutoff`, grouped by resolved category.
+fn collect_stale_files<'a>(
+    dir: &Path,
+    rules: &'a Rules,
+    options: &ScanOptions,
+    cutoff: SystemTime,
+    depth: usize,
+    grouped: &mut BTreeMap<&'a str, Vec<PathBuf>>,
+) -> Result<()> {
     for entry in fs::read_dir(dir).with_context(|| format!("Reading {}", dir.display()))? {
         let entry = entry?;
         let path = entry.path();
         let file_name = entry.file_name();
         let file_name_str = file_name.to_string_lossy();
 
-        if !include_hidden && file_name_str.starts_with('.') {
+        if !options.include_hidden && file_name_str.starts_with('.') {
             continue;
         }
-
-        // Skip common temporary Office files
         if file_name_str.starts_with("~$") {
             continue;
         }
 
         let file_type = entry.file_type()?;
         if file_type.is_dir() {
-            // Recurse if allowed depth > 0, but do not move folders at root level
             if depth > 0 {
-                let child_moves = build_plan(&path, depth - 1, include_hidden)?;
-                moves.extend(child_moves);
+                collect_stale_files(&path, rules, options, cutoff, depth - 1, grouped)?;
             }
             continue;
         }
-
         if !file_type.is_file() {
             continue;
         }
 
-        let ext = path
-            .extension()
-            .and_then(OsStr::to_str)
-            .map(|s| s.to_lowercase())
-            .unwrap_or_else(|| String::from(""));
+        let modified = entry
+            .metadata()
+            .with_context(|| format!("Reading metadata for {}", path.display()))?
+            .modified()
+            .with_context(|| format!("Reading modified time for {}", path.display()))?;
+        if modified >= cutoff {
+            continue;
+        }
+
+        let category = classify_file(&path, &file_name_str, rules, options.by_content)?;
+        grouped.entry(category).or_default().push(path);
+    }
 
-        let category = match_category(ext.as_str());
-        let category = category.unwrap_or("Others");
+    Ok(())
+}
 
-        let dest_dir = dir.join(category);
-        let dest_path = unique_destination(&dest_dir, path.file_name().unwrap());
-        if path != dest_path {
-            moves.push((path, dest_path));
+/// `lzma_sys::LZMA_PRESET_EXTREME`, inlined to avoid a direct dependency on
+/// the sys crate just for one flag.
+const LZMA_PRESET_EXTREME: u32 = 1 << 31;
+
+/// Compress `files` into a single `.tar.xz` at `tarball`, tuned for ratio
+/// (preset 9 extreme with a 64 MiB dictionary) since these are cold files
+/// being archived once rather than read back often. Entries are named by
+/// their path relative to `root` rather than just the file name, so two
+/// same-named files from different scanned subdirectories don't collide
+/// inside the tarball. Refuses to run if `tarball` already exists, since
+/// archiving truncates and the caller deletes originals right after —
+/// silently overwriting an earlier bundle for the same category/year would
+/// destroy whatever it held with no way to get it back.
+fn create_archive(root: &Path, tarball: &Path, files: &[PathBuf]) -> Result<()> {
+    if tarball.exists() {
+        anyhow::bail!(
+            "Archive {} already exists; move it aside before archiving this category again \
+             (refusing to overwrite it, since that would destroy whatever it already holds)",
+            tarball.display()
+        );
+    }
+
+    if let Some(parent) = tarball.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Create {}", parent.display()))?;
+    }
+
+    let mut lzma_opts =
+        xz2::stream::LzmaOptions::new_preset(9 | LZMA_PRESET_EXTREME).context("Configuring xz compression")?;
+    lzma_opts.dict_size(64 * 1024 * 1024);
+    let mut filters = xz2::stream::Filters::new();
+    filters.lzma2(&lzma_opts);
+    let stream = xz2::stream::Stream::new_stream_encoder(&filters, xz2::stream::Check::Crc64)
+        .context("Initializing xz encoder")?;
+
+    let file = fs::File::create(tarball).with_context(|| format!("Create {}", tarball.display()))?;
+    let mut builder = tar::Builder::new(xz2::write::XzEncoder::new_stream(file, stream));
+
+    for path in files {
+        let name = path
+            .strip_prefix(root)
+            .with_context(|| format!("{} is not under {}", path.display(), root.display()))?;
+        builder
+            .append_path_with_name(path, name)
+            .with_context(|| format!("Archiving {} into {}", path.display(), tarball.display()))?;
+    }
+
+    let encoder = builder.into_inner().with_context(|| format!("Finalizing {}", tarball.display()))?;
+    encoder.finish().with_context(|| format!("Finalizing {}", tarball.display()))?;
+    Ok(())
+}
+
+/// Verify `tarball` holds exactly as many entries as `files`, with no two
+/// entries sharing the same archived name, before the caller deletes the
+/// originals.
+fn verify_archive(tarball: &Path, files: &[PathBuf]) -> Result<()> {
+    let file = fs::File::open(tarball).with_context(|| format!("Opening {}", tarball.display()))?;
+    let mut archive = tar::Archive::new(xz2::read::XzDecoder::new(file));
+
+    let mut seen = HashSet::new();
+    let mut entry_count = 0usize;
+    for entry in archive
+        .entries()
+        .with_context(|| format!("Reading entries from {}", tarball.display()))?
+    {
+        let entry = entry.with_context(|| format!("Reading an entry from {}", tarball.display()))?;
+        let name = entry
+            .path()
+            .with_context(|| format!("Reading an entry path from {}", tarball.display()))?
+            .into_owned();
+        entry_count += 1;
+        if !seen.insert(name.clone()) {
+            anyhow::bail!(
+                "Archive {} contains duplicate entry {}; refusing to delete originals",
+                tarball.display(),
+                name.display()
+            );
         }
     }
 
-    Ok(moves)
+    if entry_count != files.len() {
+        anyhow::bail!(
+            "Archive {} has {} entries, expected {}; refusing to delete originals",
+            tarball.display(),
+            entry_count,
+            files.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// A single planned filesystem operation.
+enum Action {
+    Move { from: PathBuf, to: PathBuf },
+    Skip { from: PathBuf, duplicate_of: PathBuf },
+    Delete { from: PathBuf, duplicate_of: PathBuf },
 }
 
-fn match_category(ext: &str) -> Option<&'static str> {
-    if ext.is_empty() {
-        return None;
+impl Action {
+    fn source(&self) -> &Path {
+        match self {
+            Action::Move { from, .. } => from,
+            Action::Skip { from, .. } => from,
+            Action::Delete { from, .. } => from,
+        }
     }
-    for (category, extensions) in CATEGORY_EXTENSIONS.iter() {
-        if extensions.contains(ext) {
-            return Some(category);
+}
+
+/// Knobs governing how a scan classifies and places files. Bundled together
+/// because [`build_plan`] threads them unchanged through every worker and
+/// recursive call.
+struct ScanOptions {
+    depth: usize,
+    include_hidden: bool,
+    by_content: bool,
+    on_duplicate: OnDuplicate,
+    follow_symlinks: bool,
+    by_date: bool,
+    date_format: String,
+}
+
+/// Output of [`build_plan`]: the ordered actions plus any non-fatal issues
+/// (broken or cyclic symlinks) encountered along the way.
+struct ScanResult {
+    plan: Vec<Action>,
+    warnings: Vec<String>,
+}
+
+/// Maximum number of symlink hops allowed along a single traversal path
+/// before it is treated as a likely cycle and abandoned.
+const MAX_SYMLINK_JUMPS: usize = 20;
+
+/// Shared state for detecting symlink cycles across the whole scan. Every
+/// directory's canonical path is recorded here the first time it's scanned
+/// — not just ones reached via a symlink — so a symlink anywhere in the
+/// tree that resolves back to an already-scanned directory is caught on
+/// its first occurrence. Only directories are tracked: file symlinks are
+/// leaves and never recurse, so two unrelated file symlinks pointing at
+/// the same target are not a cycle and must not collide in this set.
+struct SymlinkTracker {
+    follow: bool,
+    visited: Mutex<HashSet<PathBuf>>,
+}
+
+/// Scan `dir` with a work-queue of directories drained by a pool of rayon
+/// workers: each worker reads one directory off the queue, resolves its
+/// files into actions, and pushes any subdirectories back onto the queue
+/// until the depth budget is exhausted. The final plan is sorted so output
+/// stays deterministic despite the unordered parallel scan.
+fn build_plan(dir: &Path, rules: &Rules, options: &ScanOptions) -> Result<ScanResult> {
+    let queue: Mutex<VecDeque<(PathBuf, usize, usize)>> =
+        Mutex::new(VecDeque::from([(dir.to_path_buf(), options.depth, 0)]));
+    let pending = AtomicUsize::new(1);
+    let actions: Mutex<Vec<Action>> = Mutex::new(Vec::new());
+    let warnings: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let error: Mutex<Option<anyhow::Error>> = Mutex::new(None);
+    let symlinks = SymlinkTracker { follow: options.follow_symlinks, visited: Mutex::new(HashSet::new()) };
+
+    // Register the root itself, so a symlink anywhere in the tree that
+    // resolves back to it is caught as a cycle on first occurrence.
+    let root_canonical =
+        fs::canonicalize(dir).with_context(|| format!("Resolving {}", dir.display()))?;
+    symlinks.visited.lock().unwrap().insert(root_canonical);
+
+    let num_workers = rayon::current_num_threads().max(1);
+    rayon::scope(|scope| {
+        for _ in 0..num_workers {
+            scope.spawn(|_| loop {
+                if error.lock().unwrap().is_some() {
+                    return;
+                }
+
+                let next = queue.lock().unwrap().pop_front();
+                let (dir, depth_budget, jumps) = match next {
+                    Some(item) => item,
+                    None => {
+                        if pending.load(Ordering::SeqCst) == 0 {
+                            return;
+                        }
+                        std::thread::yield_now();
+                        continue;
+                    }
+                };
+
+                match scan_directory(&dir, rules, options, &symlinks, jumps) {
+                    Ok((dir_actions, subdirs, dir_warnings)) => {
+                        actions.lock().unwrap().extend(dir_actions);
+                        warnings.lock().unwrap().extend(dir_warnings);
+                        if depth_budget > 0 {
+                            pending.fetch_add(subdirs.len(), Ordering::SeqCst);
+                            let mut queue = queue.lock().unwrap();
+                            queue.extend(
+                                subdirs.into_iter().map(|(sub, sub_jumps)| (sub, depth_budget - 1, sub_jumps)),
+                            );
+                        }
+                    }
+                    Err(err) => *error.lock().unwrap() = Some(err),
+                }
+
+                pending.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    if let Some(err) = error.into_inner().unwrap() {
+        return Err(err);
+    }
+
+    let mut warnings = warnings.into_inner().unwrap();
+    let mut actions = dedup_by_canonical_source(actions.into_inner().unwrap(), &mut warnings);
+    actions.sort_by(|a, b| a.source().cmp(b.source()));
+    Ok(ScanResult { plan: actions, warnings })
+}
+
+/// Drop actions whose source resolves to the same directory entry as one
+/// already kept. The `visited` cycle guard should prevent the same
+/// directory from being scanned twice via two different paths (e.g. a real
+/// directory and a symlink back to it), but this is a last-line backstop:
+/// cheap insurance against ever producing two `Move`s for one underlying
+/// file, which would otherwise make the second move fail after the first
+/// already relocated it.
+fn dedup_by_canonical_source(actions: Vec<Action>, warnings: &mut Vec<String>) -> Vec<Action> {
+    let mut seen = HashSet::new();
+    let mut deduped = Vec::with_capacity(actions.len());
+
+    for action in actions {
+        let key = canonical_entry_key(action.source());
+        let is_duplicate = match &key {
+            Some(key) => !seen.insert(key.clone()),
+            None => false,
+        };
+
+        if is_duplicate {
+            warnings.push(format!(
+                "skipping duplicate action for {} (same file reached via another path)",
+                action.source().display()
+            ));
+            continue;
+        }
+
+        deduped.push(action);
+    }
+
+    deduped
+}
+
+/// A key identifying a directory entry by its literal file name under its
+/// *canonicalized parent directory*. Deliberately resolves only the parent
+/// chain, not the entry itself: this catches the same file being reached
+/// through two different (but canonically identical) parent directories,
+/// without conflating two distinct symlinks that happen to point at the
+/// same target — those are different directory entries, not duplicates.
+fn canonical_entry_key(path: &Path) -> Option<PathBuf> {
+    let parent = path.parent()?;
+    let canonical_parent = fs::canonicalize(parent).ok()?;
+    Some(canonical_parent.join(path.file_name()?))
+}
+
+/// What an entry in a scanned directory turned out to be, after resolving
+/// symlinks according to the configured policy.
+enum EntryKind {
+    Dir,
+    File,
+    Skip,
+}
+
+/// Classify a directory entry, following and guarding against symlinks per
+/// `symlinks`'s policy. Broken symlinks and suspected cycles are recorded
+/// into `warnings` rather than failing the scan.
+fn classify_entry(
+    path: &Path,
+    file_type: fs::FileType,
+    symlinks: &SymlinkTracker,
+    jumps: usize,
+    warnings: &mut Vec<String>,
+) -> EntryKind {
+    if file_type.is_dir() {
+        // Register plain (non-symlink) directories too, not just ones
+        // reached via a symlink: a symlink elsewhere in the tree pointing
+        // back to this directory is a cycle on its first occurrence, not
+        // just on some later, nested jump.
+        return match fs::canonicalize(path) {
+            Ok(canonical) => {
+                if !symlinks.visited.lock().unwrap().insert(canonical) {
+                    warnings.push(format!("skipping {} (recursion loop detected)", path.display()));
+                    EntryKind::Skip
+                } else {
+                    EntryKind::Dir
+                }
+            }
+            Err(err) => {
+                warnings.push(format!("skipping {}: {}", path.display(), err));
+                EntryKind::Skip
+            }
+        };
+    }
+    if file_type.is_file() {
+        return EntryKind::File;
+    }
+    if !file_type.is_symlink() {
+        return EntryKind::Skip;
+    }
+    if !symlinks.follow {
+        return EntryKind::Skip;
+    }
+    if jumps >= MAX_SYMLINK_JUMPS {
+        warnings.push(format!(
+            "skipping symlink {} (exceeded max jump depth of {})",
+            path.display(),
+            MAX_SYMLINK_JUMPS
+        ));
+        return EntryKind::Skip;
+    }
+
+    let canonical = match fs::canonicalize(path) {
+        Ok(canonical) => canonical,
+        Err(err) => {
+            warnings.push(format!("skipping broken symlink {}: {}", path.display(), err));
+            return EntryKind::Skip;
+        }
+    };
+
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(err) => {
+            warnings.push(format!("skipping broken symlink {}: {}", path.display(), err));
+            return EntryKind::Skip;
+        }
+    };
+
+    // Only directories can recurse back on themselves, so only they need the
+    // cycle check; a file symlink is a leaf regardless of how many other
+    // symlinks point at the same target.
+    if meta.is_dir() {
+        if !symlinks.visited.lock().unwrap().insert(canonical) {
+            warnings.push(format!("skipping symlink {} (recursion loop detected)", path.display()));
+            return EntryKind::Skip;
+        }
+        return EntryKind::Dir;
+    }
+
+    if meta.is_file() {
+        return EntryKind::File;
+    }
+
+    EntryKind::Skip
+}
+
+/// A subdirectory discovered while scanning, paired with its accumulated
+/// symlink-jump count for the caller to push back onto the work-queue.
+type SubdirEntry = (PathBuf, usize);
+
+/// Result of scanning a single directory: its planned actions, the
+/// subdirectories discovered, and any non-fatal warnings.
+type ScanDirResult = (Vec<Action>, Vec<SubdirEntry>, Vec<String>);
+
+/// Process the files directly inside `dir` (non-recursively), returning the
+/// actions for them plus the subdirectories discovered (each paired with its
+/// symlink-jump count) for the caller to push back onto the work-queue.
+fn scan_directory(
+    dir: &Path,
+    rules: &Rules,
+    options: &ScanOptions,
+    symlinks: &SymlinkTracker,
+    jumps: usize,
+) -> Result<ScanDirResult> {
+    let mut actions = Vec::new();
+    let mut subdirs = Vec::new();
+    let mut warnings = Vec::new();
+
+    for entry in fs::read_dir(dir).with_context(|| format!("Reading {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name_str = file_name.to_string_lossy();
+
+        if !options.include_hidden && file_name_str.starts_with('.') {
+            continue;
+        }
+
+        // Skip common temporary Office files
+        if file_name_str.starts_with("~$") {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        let is_symlink = file_type.is_symlink();
+        match classify_entry(&path, file_type, symlinks, jumps, &mut warnings) {
+            EntryKind::Dir => {
+                // Do not move folders at root level; the caller decides
+                // whether the depth budget allows recursing into them.
+                let child_jumps = if is_symlink { jumps + 1 } else { jumps };
+                subdirs.push((path, child_jumps));
+                continue;
+            }
+            EntryKind::Skip => continue,
+            EntryKind::File => {}
+        }
+
+        let category = classify_file(&path, &file_name_str, rules, options.by_content)?;
+
+        let mut dest_dir = dir.join(category);
+        if options.by_date {
+            for component in date_subpath(&path, &options.date_format)? {
+                dest_dir = dest_dir.join(component);
+            }
         }
+
+        if let Some(action) = resolve_destination(&dest_dir, &path, options.on_duplicate)? {
+            actions.push(action);
+        }
+    }
+
+    Ok((actions, subdirs, warnings))
+}
+
+/// Decide what to do with `source` given its computed destination directory.
+///
+/// Returns `Ok(None)` when the file is already at its destination.
+fn resolve_destination(dest_dir: &Path, source: &Path, on_duplicate: OnDuplicate) -> Result<Option<Action>> {
+    let file_name = source.file_name().unwrap();
+    let mut candidate = dest_dir.join(file_name);
+
+    if source == candidate {
+        return Ok(None);
+    }
+
+    if !candidate.exists() {
+        return Ok(Some(Action::Move { from: source.to_path_buf(), to: candidate }));
+    }
+
+    if files_identical(source, &candidate)? {
+        return Ok(Some(match on_duplicate {
+            OnDuplicate::Skip => Action::Skip { from: source.to_path_buf(), duplicate_of: candidate },
+            OnDuplicate::Delete => Action::Delete { from: source.to_path_buf(), duplicate_of: candidate },
+            OnDuplicate::Rename => {
+                candidate = next_unique_name(dest_dir, file_name);
+                Action::Move { from: source.to_path_buf(), to: candidate }
+            }
+        }));
+    }
+
+    // Same name, different content: fall back to numbering a unique name.
+    candidate = next_unique_name(dest_dir, file_name);
+    Ok(Some(Action::Move { from: source.to_path_buf(), to: candidate }))
+}
+
+/// Compare two files by size first, then by a streaming content hash, to
+/// avoid hashing large files that can't possibly match.
+fn files_identical(a: &Path, b: &Path) -> Result<bool> {
+    let size_a = fs::metadata(a).with_context(|| format!("Reading metadata for {}", a.display()))?.len();
+    let size_b = fs::metadata(b).with_context(|| format!("Reading metadata for {}", b.display()))?.len();
+    if size_a != size_b {
+        return Ok(false);
+    }
+
+    Ok(hash_file(a)? == hash_file(b)?)
+}
+
+/// Render `path`'s modification time through `pattern` (a strftime-style
+/// format, e.g. "%Y/%m") and split the result on '/' into path components, so
+/// callers can `.join()` them onto a destination directory one at a time.
+fn date_subpath(path: &Path, pattern: &str) -> Result<Vec<String>> {
+    use std::fmt::Write as _;
+
+    let modified = fs::metadata(path)
+        .with_context(|| format!("Reading metadata for {}", path.display()))?
+        .modified()
+        .with_context(|| format!("Reading modified time for {}", path.display()))?;
+
+    // chrono's `Display` for an unsupported specifier returns an `Err`
+    // instead of panicking; going through `write!` lets us turn that into a
+    // normal error instead of `to_string()` turning it into a panic.
+    let mut formatted = String::new();
+    write!(formatted, "{}", DateTime::<Local>::from(modified).format(pattern))
+        .with_context(|| format!("Invalid --date-format pattern `{}`", pattern))?;
+    Ok(formatted.split('/').map(String::from).collect())
+}
+
+fn hash_file(path: &Path) -> Result<blake3::Hash> {
+    let mut file = fs::File::open(path).with_context(|| format!("Opening {}", path.display()))?;
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut file, &mut hasher).with_context(|| format!("Hashing {}", path.display()))?;
+    Ok(hasher.finalize())
+}
+
+/// Resolve a file's category from its name and (optionally) its content,
+/// falling back to "Others" when nothing matches. Shared between the
+/// move-plan scan and the archival scan so both classify files identically.
+fn classify_file<'a>(path: &Path, file_name: &str, rules: &'a Rules, by_content: bool) -> Result<&'a str> {
+    let ext = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(|s| s.to_lowercase())
+        .unwrap_or_else(|| String::from(""));
+
+    // A name_regex rule is the most specific thing a user can say, so it wins
+    // over both content sniffing and extension matching.
+    let category = match rules.match_name(file_name) {
+        Some(category) => Some(category),
+        None if by_content => sniff_category(path)
+            .with_context(|| format!("Sniffing {}", path.display()))?
+            .or_else(|| rules.match_extension(ext.as_str())),
+        None => rules.match_extension(ext.as_str()),
+    };
+
+    Ok(category.unwrap_or("Others"))
+}
+
+/// Number of leading bytes read from each file when sniffing its content.
+const SNIFF_BUF_LEN: usize = 8192;
+
+/// Classify a file by inspecting its magic bytes rather than its extension.
+///
+/// Returns `Ok(None)` when the content doesn't match any known signature, in
+/// which case the caller should fall back to extension-based matching.
+fn sniff_category(path: &Path) -> Result<Option<&'static str>> {
+    use io::Read;
+
+    let mut file = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Opening {}", path.display())),
+    };
+
+    let mut buf = [0u8; SNIFF_BUF_LEN];
+    let n = file
+        .read(&mut buf)
+        .with_context(|| format!("Reading {}", path.display()))?;
+    let head = &buf[..n];
+
+    Ok(classify_bytes(head))
+}
+
+fn classify_bytes(head: &[u8]) -> Option<&'static str> {
+    if starts_with(head, &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some("Images");
+    }
+    if starts_with(head, &[0xFF, 0xD8, 0xFF]) {
+        return Some("Images");
+    }
+    if starts_with(head, b"GIF87a") || starts_with(head, b"GIF89a") {
+        return Some("Images");
+    }
+    if starts_with(head, b"BM") {
+        return Some("Images");
+    }
+    if starts_with(head, b"%PDF-") {
+        return Some("Documents");
+    }
+    if starts_with(head, b"fLaC") {
+        return Some("Audio");
+    }
+    if starts_with(head, b"ID3") || starts_with(head, &[0xFF, 0xFB]) {
+        return Some("Audio");
+    }
+    if head.len() >= 12 && &head[0..4] == b"RIFF" && &head[8..12] == b"WAVE" {
+        return Some("Audio");
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some("Videos");
+    }
+    if starts_with(head, &[0x1F, 0x8B]) {
+        return Some("Archives");
+    }
+    if starts_with(head, &[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C]) {
+        return Some("Archives");
+    }
+    if starts_with(head, &[0x52, 0x61, 0x72, 0x21, 0x1A, 0x07]) {
+        return Some("Archives");
+    }
+    if starts_with(head, &[b'P', b'K', 0x03, 0x04])
+        || starts_with(head, &[b'P', b'K', 0x05, 0x06])
+    {
+        return Some(classify_zip(head));
+    }
+    if starts_with(head, b"MZ") {
+        return Some(classify_pe(head));
     }
     None
 }
 
-fn unique_destination(dest_dir: &Path, file_name: &OsStr) -> PathBuf {
+fn starts_with(haystack: &[u8], prefix: &[u8]) -> bool {
+    haystack.len() >= prefix.len() && &haystack[..prefix.len()] == prefix
+}
+
+/// Disambiguate a zip-family container: Office documents (docx/xlsx/pptx) share
+/// the PK magic header with plain `.zip` archives, so peek at entry names to
+/// tell them apart.
+fn classify_zip(head: &[u8]) -> &'static str {
+    if contains(head, b"word/") {
+        return "Documents";
+    }
+    if contains(head, b"xl/") {
+        return "Sheets";
+    }
+    if contains(head, b"ppt/") {
+        return "Slides";
+    }
+    "Archives"
+}
+
+/// Disambiguate an MZ/PE container: DLLs carry the `IMAGE_FILE_DLL`
+/// characteristic flag in the COFF header and go to Libraries, while EXE/SCR
+/// are indistinguishable from content alone (SCR is just a renamed EXE), so
+/// both fall into Installer.
+fn classify_pe(head: &[u8]) -> &'static str {
+    const IMAGE_FILE_DLL: u16 = 0x2000;
+
+    let pe_offset = if head.len() >= 0x40 {
+        u32::from_le_bytes([head[0x3C], head[0x3D], head[0x3E], head[0x3F]]) as usize
+    } else {
+        return "Installer";
+    };
+
+    let characteristics_offset = pe_offset + 4 + 18;
+    if pe_offset + 4 <= head.len()
+        && &head[pe_offset..pe_offset + 4] == b"PE\0\0"
+        && characteristics_offset + 2 <= head.len()
+    {
+        let characteristics = u16::from_le_bytes([
+            head[characteristics_offset],
+            head[characteristics_offset + 1],
+        ]);
+        if characteristics & IMAGE_FILE_DLL != 0 {
+            return "Libraries";
+        }
+    }
+
+    "Installer"
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Find a free name in `dest_dir` by appending " (1)", " (2)", ... before the extension.
+fn next_unique_name(dest_dir: &Path, file_name: &OsStr) -> PathBuf {
     let mut candidate = dest_dir.join(file_name);
     if !candidate.exists() {
         return candidate;
@@ -190,22 +1095,366 @@ fn unique_destination(dest_dir: &Path, file_name: &OsStr) -> PathBuf {
     }
 }
 
-fn apply_moves(moves: Vec<(PathBuf, PathBuf)>) -> Result<()> {
-    for (from, to) in moves {
-        if let Some(parent) = to.parent() {
-            fs::create_dir_all(parent).with_context(|| format!("Create {}", parent.display()))?;
-        }
+fn apply_moves(actions: Vec<Action>) -> Result<()> {
+    let mut journal = Journal::start()?;
+
+    for action in actions {
+        match action {
+            Action::Move { from, to } => {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent).with_context(|| format!("Create {}", parent.display()))?;
+                }
+
+                // Use rename first; if cross-filesystem, fallback to copy + remove
+                match fs::rename(&from, &to) {
+                    Ok(_) => {}
+                    Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                        fs::copy(&from, &to)
+                            .with_context(|| format!("Copy {} -> {}", from.display(), to.display()))?;
+                        fs::remove_file(&from).with_context(|| format!("Remove {}", from.display()))?;
+                    }
+                    Err(err) => {
+                        return Err(err).with_context(|| format!("Move {} -> {}", from.display(), to.display()))
+                    }
+                }
 
-        // Use rename first; if cross-filesystem, fallback to copy + remove
-        match fs::rename(&from, &to) {
-            Ok(_) => {}
-            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
-                fs::copy(&from, &to)
-                    .with_context(|| format!("Copy {} -> {}", from.display(), to.display()))?;
+                journal.record(&from, &to)?;
+            }
+            Action::Skip { .. } => {}
+            Action::Delete { from, .. } => {
                 fs::remove_file(&from).with_context(|| format!("Remove {}", from.display()))?;
             }
-            Err(err) => return Err(err).with_context(|| format!("Move {} -> {}", from.display(), to.display())),
         }
     }
     Ok(())
 }
+
+/// One journaled move: where a file used to be, where it was moved to, and
+/// when. Recorded as its own JSON-lines entry so `foldean undo` can replay
+/// a run in reverse without re-deriving any classification logic.
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    from: PathBuf,
+    to: PathBuf,
+    timestamp: u64,
+}
+
+/// An append-only log of the moves made by one `--apply` run, written under
+/// [`foldean_state_dir`]`/journal/<run timestamp>.jsonl`. If the platform has
+/// no resolvable config dir, journaling is silently skipped; `--apply` still
+/// works, it just loses the undo safety net.
+struct Journal {
+    file: Option<fs::File>,
+}
+
+impl Journal {
+    fn start() -> Result<Journal> {
+        let Some(dir) = foldean_state_dir().map(|dir| dir.join("journal")) else {
+            return Ok(Journal { file: None });
+        };
+        fs::create_dir_all(&dir).with_context(|| format!("Create {}", dir.display()))?;
+
+        let run_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System clock is before the Unix epoch")?
+            .as_millis();
+        let path = dir.join(format!("{}.jsonl", run_id));
+        let file = fs::File::create(&path).with_context(|| format!("Create {}", path.display()))?;
+        Ok(Journal { file: Some(file) })
+    }
+
+    fn record(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System clock is before the Unix epoch")?
+            .as_secs();
+        let entry = JournalEntry {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            timestamp,
+        };
+        serde_json::to_writer(&mut *file, &entry).context("Writing journal entry")?;
+        writeln!(file).context("Writing journal entry")?;
+        Ok(())
+    }
+}
+
+/// Replay the most recently written journal in reverse, moving each file
+/// back to where it came from. Entries whose destination no longer exists
+/// (already moved, renamed, or deleted since) are reported and skipped
+/// rather than failing the whole undo, and entries whose original path has
+/// since been reoccupied by different content are likewise skipped and
+/// reported rather than silently overwritten.
+fn run_undo() -> Result<()> {
+    let Some(journal_dir) = foldean_state_dir().map(|dir| dir.join("journal")) else {
+        println!("No config directory available on this platform; nothing to undo.");
+        return Ok(());
+    };
+
+    let Some(path) = most_recent_journal(&journal_dir)? else {
+        println!("No journaled run found to undo.");
+        return Ok(());
+    };
+
+    let contents = fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+    let mut entries = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<JournalEntry>(line)
+                .with_context(|| format!("Parsing journal entry in {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.reverse();
+
+    let mut restored = 0usize;
+    let mut skipped = 0usize;
+    for entry in &entries {
+        if !entry.to.exists() {
+            println!("  skip: {} no longer exists", entry.to.display());
+            skipped += 1;
+            continue;
+        }
+
+        if entry.from.exists() {
+            if files_identical(&entry.to, &entry.from)? {
+                println!("  skip: {} already restored at {}", entry.to.display(), entry.from.display());
+            } else {
+                println!(
+                    "  skip: {} already exists with different content; not overwriting",
+                    entry.from.display()
+                );
+            }
+            skipped += 1;
+            continue;
+        }
+
+        if let Some(parent) = entry.from.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Create {}", parent.display()))?;
+        }
+        fs::rename(&entry.to, &entry.from)
+            .with_context(|| format!("Restoring {} -> {}", entry.to.display(), entry.from.display()))?;
+        restored += 1;
+    }
+
+    fs::remove_file(&path).with_context(|| format!("Remove {}", path.display()))?;
+    println!("Restored {} file(s), skipped {} (see above for why).", restored, skipped);
+    Ok(())
+}
+
+/// The most recently modified `*.jsonl` journal file in `dir`, if any.
+fn most_recent_journal(dir: &Path) -> Result<Option<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Reading {}", dir.display())),
+    };
+
+    let mut latest: Option<(SystemTime, PathBuf)> = None;
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().extension().and_then(OsStr::to_str) != Some("jsonl") {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if latest.as_ref().is_none_or(|(newest, _)| modified > *newest) {
+            latest = Some((modified, entry.path()));
+        }
+    }
+    Ok(latest.map(|(_, path)| path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh scratch directory under the OS temp dir, unique to this test
+    /// process and case name, removed again once the caller is done with it.
+    struct ScratchDir(PathBuf);
+
+    impl std::ops::Deref for ScratchDir {
+        type Target = Path;
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn scratch_dir(case: &str) -> ScratchDir {
+        let dir = std::env::temp_dir().join(format!("foldean_test_{}_{}", std::process::id(), case));
+        fs::create_dir_all(&dir).unwrap();
+        ScratchDir(dir)
+    }
+
+    fn default_scan_options() -> ScanOptions {
+        ScanOptions {
+            depth: 10,
+            include_hidden: false,
+            by_content: false,
+            on_duplicate: OnDuplicate::Rename,
+            follow_symlinks: true,
+            by_date: false,
+            date_format: "%Y/%m".to_string(),
+        }
+    }
+
+    #[test]
+    fn classify_bytes_detects_png_by_magic_header() {
+        let head = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0];
+        assert_eq!(classify_bytes(&head), Some("Images"));
+    }
+
+    #[test]
+    fn classify_bytes_detects_pdf_by_magic_header() {
+        assert_eq!(classify_bytes(b"%PDF-1.7 rest of file"), Some("Documents"));
+    }
+
+    #[test]
+    fn classify_bytes_returns_none_for_unrecognized_content() {
+        assert_eq!(classify_bytes(b"just plain text, no magic header"), None);
+    }
+
+    #[test]
+    fn classify_zip_disambiguates_office_documents_from_plain_zip() {
+        let mut word_head = vec![b'P', b'K', 0x03, 0x04];
+        word_head.extend_from_slice(b"word/document.xml");
+        assert_eq!(classify_zip(&word_head), "Documents");
+
+        let mut sheet_head = vec![b'P', b'K', 0x03, 0x04];
+        sheet_head.extend_from_slice(b"xl/worksheets/sheet1.xml");
+        assert_eq!(classify_zip(&sheet_head), "Sheets");
+
+        let mut slide_head = vec![b'P', b'K', 0x03, 0x04];
+        slide_head.extend_from_slice(b"ppt/presentation.xml");
+        assert_eq!(classify_zip(&slide_head), "Slides");
+
+        let plain_head = [b'P', b'K', 0x03, 0x04, b'r', b'e', b's', b't'];
+        assert_eq!(classify_zip(&plain_head), "Archives");
+    }
+
+    /// Build a minimal MZ/PE header with the COFF characteristics field set
+    /// to `characteristics`, for exercising [`classify_pe`].
+    fn pe_head(characteristics: u16) -> Vec<u8> {
+        let pe_offset: usize = 0x40;
+        let mut head = vec![0u8; pe_offset + 4 + 20];
+        head[0x3C..0x40].copy_from_slice(&(pe_offset as u32).to_le_bytes());
+        head[pe_offset..pe_offset + 4].copy_from_slice(b"PE\0\0");
+        let characteristics_offset = pe_offset + 4 + 18;
+        head[characteristics_offset..characteristics_offset + 2]
+            .copy_from_slice(&characteristics.to_le_bytes());
+        head
+    }
+
+    #[test]
+    fn classify_pe_routes_dll_characteristic_flag_to_libraries() {
+        assert_eq!(classify_pe(&pe_head(0x2000)), "Libraries");
+    }
+
+    #[test]
+    fn classify_pe_without_dll_flag_falls_back_to_installer() {
+        assert_eq!(classify_pe(&pe_head(0x0000)), "Installer");
+    }
+
+    #[test]
+    fn files_identical_true_for_matching_content() {
+        let dir = scratch_dir("files_identical_match");
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, b"same content").unwrap();
+        fs::write(&b, b"same content").unwrap();
+        assert!(files_identical(&a, &b).unwrap());
+    }
+
+    #[test]
+    fn files_identical_false_for_same_size_different_content() {
+        let dir = scratch_dir("files_identical_same_size");
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, b"aaa").unwrap();
+        fs::write(&b, b"bbb").unwrap();
+        assert!(!files_identical(&a, &b).unwrap());
+    }
+
+    #[test]
+    fn files_identical_false_for_different_size_short_circuits_before_hashing() {
+        let dir = scratch_dir("files_identical_diff_size");
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, b"short").unwrap();
+        fs::write(&b, b"much longer content").unwrap();
+        assert!(!files_identical(&a, &b).unwrap());
+    }
+
+    #[test]
+    fn create_archive_refuses_to_overwrite_an_existing_tarball() {
+        let dir = scratch_dir("archive_repeat_run");
+        let file = dir.join("a.txt");
+        fs::write(&file, b"contents").unwrap();
+        let tarball = dir.join("Documents-2026.tar.xz");
+
+        create_archive(&dir, &tarball, std::slice::from_ref(&file)).unwrap();
+        assert!(tarball.exists());
+        let original_size = fs::metadata(&tarball).unwrap().len();
+
+        let err = create_archive(&dir, &tarball, &[file]).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(
+            fs::metadata(&tarball).unwrap().len(),
+            original_size,
+            "a refused second run must not touch the existing archive"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle_back_to_an_ancestor_is_caught_on_first_occurrence() {
+        let dir = scratch_dir("symlink_cycle");
+        let sub = dir.join("sub");
+        let deeper = sub.join("deeper");
+        fs::create_dir_all(&deeper).unwrap();
+        fs::write(deeper.join("file.txt"), b"content").unwrap();
+        // Points back up at `sub`, so walking into it revisits an
+        // already-scanned ancestor directory rather than a sibling.
+        std::os::unix::fs::symlink(&sub, sub.join("loopback")).unwrap();
+
+        let rules = Rules::resolve(ConfigFile::default()).unwrap();
+        let scan = build_plan(&dir, &rules, &default_scan_options()).unwrap();
+
+        assert_eq!(scan.plan.len(), 1, "the cycle must not produce a duplicate move for the same file");
+        assert!(scan.warnings.iter().any(|w| w.contains("recursion loop detected")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unrelated_symlinks_to_the_same_file_are_not_mistaken_for_a_cycle() {
+        let dir = scratch_dir("symlink_fanout");
+        let dir_a = dir.join("dirA");
+        let dir_b = dir.join("dirB");
+        let store = dir.join("store");
+        fs::create_dir_all(&dir_a).unwrap();
+        fs::create_dir_all(&dir_b).unwrap();
+        fs::create_dir_all(&store).unwrap();
+        let target = store.join("shared.txt");
+        fs::write(&target, b"shared").unwrap();
+        std::os::unix::fs::symlink(&target, dir_a.join("a.txt")).unwrap();
+        std::os::unix::fs::symlink(&target, dir_b.join("b.txt")).unwrap();
+
+        let rules = Rules::resolve(ConfigFile::default()).unwrap();
+        let scan = build_plan(&dir, &rules, &default_scan_options()).unwrap();
+
+        assert_eq!(
+            scan.plan.len(),
+            3,
+            "three distinct directory entries pointing at the same content must each get their own action"
+        );
+    }
+}